@@ -26,7 +26,8 @@
 //   fundamentales de `iced`.
 // - `alignment`, `Color`, `Size`, `window`: Utilidades para el estilo y la configuración
 //   de la ventana.
-use iced::{border, executor, widget::{button, column, row, text, container, Space}, Application, Command, Element, Length, Settings, Theme, alignment, Color, Size, window};
+use std::collections::HashMap;
+use iced::{border, executor, keyboard, widget::{button, column, row, text, container, scrollable, Space}, Application, Command, Element, Length, Settings, Subscription, Theme, alignment, Color, Size, window};
 
 // # 3. Carga del Icono
 //
@@ -56,6 +57,16 @@ enum Message {
     EqualsPressed,
     ClearPressed,
     ParenthesisPressed(char),
+    FunctionPressed(String),
+    MemoryAdd,
+    MemoryRecall,
+    MemoryClear,
+    ToggleVm,
+    Backspace,
+    ClearEntry,
+    AnsPressed,
+    HistorySelected(usize),
+    CharacterPressed(char),
 }
 
 // # 5. Estructura de la Calculadora (El Modelo)
@@ -65,9 +76,19 @@ enum Message {
 //
 // - `expression`: Almacena la expresión matemática que el usuario está introduciendo.
 // - `result`: Almacena el resultado del último cálculo.
+// - `variables`: Entorno de variables definidas por el usuario (`x = 3 * 4`) y,
+//   bajo la clave reservada `"M"`, el registro de memoria clásico (M+, MR, MC).
+// - `use_vm`: si está activo, `EqualsPressed` evalúa compilando el AST a
+//   bytecode y ejecutándolo en la máquina de pila (`compile` + `run_vm`) en
+//   lugar de recorrer el AST directamente con `evaluate_ast`.
+// - `history`: pares `(expresión, resultado)` de cada cálculo exitoso, del más
+//   antiguo al más reciente, para poder revisarlos y reutilizarlos.
 struct Calculator {
     expression: String,
     result: String,
+    variables: HashMap<String, f64>,
+    use_vm: bool,
+    history: Vec<(String, String)>,
 }
 
 // # 6. Implementación del `trait` `Application`
@@ -92,6 +113,9 @@ impl Application for Calculator {
             Calculator {
                 expression: String::new(),
                 result: String::new(),
+                variables: HashMap::new(),
+                use_vm: false,
+                history: Vec::new(),
             },
             Command::none(),
         )
@@ -110,30 +134,54 @@ impl Application for Calculator {
                 self.expression.push(digit);
             }
             Message::OperationPressed(op) => {
-                self.expression.push(' ');
-                self.expression.push(op);
-                self.expression.push(' ');
+                // El factorial es un operador postfijo: se pega al número anterior
+                // en lugar de ir rodeado de espacios como los operadores binarios.
+                if op == '!' {
+                    self.expression.push(op);
+                } else {
+                    self.expression.push(' ');
+                    self.expression.push(op);
+                    self.expression.push(' ');
+                }
             }
             Message::ParenthesisPressed(paren) => {
                 self.expression.push(paren);
             }
+            Message::FunctionPressed(name) => {
+                self.expression.push_str(&name);
+                self.expression.push('(');
+            }
             Message::EqualsPressed => {
                 // Aquí es donde se produce la magia:
                 // 1. `lexer`: Convierte la cadena de expresión en una secuencia de `Token`s.
                 // 2. `Parser::new(tokens).parse()`: Convierte los `Token`s en un Árbol de
                 //    Sintaxis Abstracta (AST).
-                // 3. `evaluate_ast`: Evalúa el AST para obtener el resultado.
+                // 3. `evaluate_ast` (o, con `use_vm` activo, `compile` + `run_vm`): evalúa
+                //    el AST para obtener el resultado.
                 match lexer(&self.expression).and_then(|tokens| Parser::new(tokens).parse()) {
-                    Ok(ast) => match evaluate_ast(&ast) {
-                        Ok(res) => {
-                            self.result = res.to_string();
-                            self.expression.clear();
-                        }
-                        Err(e) => {
-                            self.result = format!("Error: {}", e);
-                            self.expression.clear();
+                    Ok(ast) => {
+                        let evaluation = if self.use_vm {
+                            let mut code = Vec::new();
+                            compile(&ast, &mut code).and_then(|_| run_vm(&code))
+                        } else {
+                            evaluate_ast(&ast, &mut self.variables)
+                        };
+                        match evaluation {
+                            Ok(res) => {
+                                self.result = res.to_string();
+                                self.history.push((self.expression.clone(), self.result.clone()));
+                                // `Ans` es simplemente otra entrada reservada en el entorno de
+                                // variables, igual que `M`, así que la resolución de `Ans` en
+                                // una expresión reutiliza la búsqueda de `Expr::Variable`.
+                                self.variables.insert("Ans".to_string(), res);
+                                self.expression.clear();
+                            }
+                            Err(e) => {
+                                self.result = format!("Error: {}", e);
+                                self.expression.clear();
+                            }
                         }
-                    },
+                    }
                     Err(e) => {
                         self.result = format!("Error: {}", e);
                         self.expression.clear();
@@ -144,6 +192,58 @@ impl Application for Calculator {
                 self.expression.clear();
                 self.result.clear();
             }
+            Message::MemoryAdd => {
+                if let Ok(value) = self.result.parse::<f64>() {
+                    *self.variables.entry("M".to_string()).or_insert(0.0) += value;
+                }
+            }
+            Message::MemoryRecall => {
+                if let Some(value) = self.variables.get("M") {
+                    self.result = value.to_string();
+                }
+            }
+            Message::MemoryClear => {
+                self.variables.remove("M");
+            }
+            Message::ToggleVm => {
+                self.use_vm = !self.use_vm;
+            }
+            Message::Backspace => {
+                // Quitamos primero cualquier espacio final y, si lo que queda termina
+                // en un operador binario (que siempre va rodeado de espacios), lo
+                // borramos junto con el espacio que lo precede; en otro caso borramos
+                // un único carácter (dígito, paréntesis, `!`, ...).
+                while self.expression.ends_with(' ') {
+                    self.expression.pop();
+                }
+                if let Some(last) = self.expression.chars().last() {
+                    if "+-*/^".contains(last) {
+                        self.expression.pop();
+                        while self.expression.ends_with(' ') {
+                            self.expression.pop();
+                        }
+                    } else {
+                        self.expression.pop();
+                    }
+                }
+            }
+            Message::ClearEntry => {
+                self.expression.clear();
+            }
+            Message::AnsPressed => {
+                self.expression.push_str("Ans");
+            }
+            Message::HistorySelected(index) => {
+                if let Some((_, result)) = self.history.get(index) {
+                    self.expression = result.clone();
+                }
+            }
+            Message::CharacterPressed(c) => {
+                // Letras sueltas (nombres de variable) y `=` (asignación): se
+                // insertan tal cual, sin el espaciado que llevan los operadores
+                // binarios, para que letras seguidas formen un único identificador.
+                self.expression.push(c);
+            }
         }
         // `Command::none()` indica que no se debe ejecutar ningún comando asíncrono.
         Command::none()
@@ -201,6 +301,14 @@ impl Application for Calculator {
             .on_press(Message::EqualsPressed)
             .width(Length::Fill);
 
+        let clear_entry_button = button(text("CE").size(30).horizontal_alignment(alignment::Horizontal::Center))
+            .on_press(Message::ClearEntry)
+            .width(Length::Fill);
+
+        let backspace_button = button(text("⌫").size(30).horizontal_alignment(alignment::Horizontal::Center))
+            .on_press(Message::Backspace)
+            .width(Length::Fill);
+
         // Usamos una clausura (`closure`) para crear los botones numéricos.
         let num_button = |digit: char| {
             button(text(digit).size(30).horizontal_alignment(alignment::Horizontal::Center))
@@ -221,22 +329,105 @@ impl Application for Calculator {
                 .width(Length::Fill)
         };
 
+        // Botones para las funciones científicas (`sin`, `cos`, `tan`, `sqrt`, `ln`).
+        // Cada uno inserta `"nombre("` en la expresión, listo para que el usuario
+        // escriba el argumento y cierre el paréntesis.
+        let fn_button = |name: &'static str| {
+            button(text(name).size(20).horizontal_alignment(alignment::Horizontal::Center))
+                .on_press(Message::FunctionPressed(name.to_string()))
+                .width(Length::Fill)
+        };
+
+        // Botones del registro de memoria clásico: sumar el resultado actual,
+        // mostrar lo guardado en el display del resultado y borrarlo.
+        let memory_add_button = button(text("M+").size(20).horizontal_alignment(alignment::Horizontal::Center))
+            .on_press(Message::MemoryAdd)
+            .width(Length::Fill);
+
+        let memory_recall_button = button(text("MR").size(20).horizontal_alignment(alignment::Horizontal::Center))
+            .on_press(Message::MemoryRecall)
+            .width(Length::Fill);
+
+        let memory_clear_button = button(text("MC").size(20).horizontal_alignment(alignment::Horizontal::Center))
+            .on_press(Message::MemoryClear)
+            .width(Length::Fill);
+
+        // Alterna entre el evaluador que recorre el AST y la máquina de pila
+        // (`compile` + `run_vm`), para poder comparar ambos backends.
+        let vm_toggle_label = if self.use_vm { "VM: ON" } else { "VM: OFF" };
+        let vm_toggle_button = button(text(vm_toggle_label).size(20).horizontal_alignment(alignment::Horizontal::Center))
+            .on_press(Message::ToggleVm)
+            .width(Length::Fill);
+
+        // `Ans` inserta el literal en la expresión; el lexer lo trata como un
+        // identificador más y `evaluate_ast` lo resuelve buscando la clave
+        // reservada `"Ans"` en el entorno de variables.
+        let ans_button = button(text("Ans").size(20).horizontal_alignment(alignment::Horizontal::Center))
+            .on_press(Message::AnsPressed)
+            .width(Length::Fill);
+
+        // Panel de historial: cada entrada es un botón que, al pulsarse, recupera
+        // su resultado en la expresión actual para seguir calculando a partir de él.
+        let history_entries: Element<'_, Message> = if self.history.is_empty() {
+            text("Sin historial todavía").size(16).into()
+        } else {
+            let mut entries = column![].spacing(4);
+            for (index, (expression, result)) in self.history.iter().enumerate() {
+                let label = format!("{} = {}", expression, result);
+                entries = entries.push(
+                    button(text(label).size(16))
+                        .on_press(Message::HistorySelected(index))
+                        .width(Length::Fill),
+                );
+            }
+            entries.into()
+        };
+        let history_panel = scrollable(history_entries).height(Length::Fixed(100.0));
+
         // Organizamos los widgets en una columna (`column!`).
         column![
             expression_display,
             result_display,
+            history_panel,
             Space::with_height(Length::Fixed(10.0)), // Espacio entre el display y los botones
             // Cada fila (`row!`) de botones tiene un espaciado.
-            row![clear_button, paren_button('('), paren_button(')'), op_button('/')].spacing(10),
+            row![memory_add_button, memory_recall_button, memory_clear_button, vm_toggle_button, ans_button].spacing(10),
+            row![fn_button("sin"), fn_button("cos"), fn_button("tan"), fn_button("sqrt"), fn_button("ln")].spacing(10),
+            row![clear_button, clear_entry_button, backspace_button, paren_button('('), paren_button(')'), op_button('/')].spacing(10),
             row![num_button('7'), num_button('8'), num_button('9'), op_button('*')].spacing(10),
             row![num_button('4'), num_button('5'), num_button('6'), op_button('-')].spacing(10),
             row![num_button('1'), num_button('2'), num_button('3'), op_button('+')].spacing(10),
-            row![num_button('0'), num_button('.'), equals_button].spacing(10),
+            row![num_button('0'), num_button('.'), op_button('!'), op_button('^'), equals_button].spacing(10),
         ]
         .padding(20)
         .spacing(10)
         .into()
     }
+
+    // `subscription` deja que la aplicación reaccione a eventos externos, en
+    // este caso el teclado físico: dígitos, operadores, paréntesis, Enter,
+    // Retroceso, Escape y Supr producen el mismo `Message` que el botón
+    // equivalente; las letras y `=` se insertan como texto en la expresión.
+    fn subscription(&self) -> Subscription<Message> {
+        keyboard::on_key_press(|key, _modifiers| match key {
+            keyboard::Key::Character(c) => match c.chars().next()? {
+                digit @ '0'..='9' => Some(Message::NumberPressed(digit)),
+                '.' => Some(Message::NumberPressed('.')),
+                op @ ('+' | '-' | '*' | '/' | '^' | '!') => Some(Message::OperationPressed(op)),
+                paren @ ('(' | ')') => Some(Message::ParenthesisPressed(paren)),
+                // Letras y `=` permiten escribir asignaciones y variables
+                // completas (`x = 3 * 4`) directamente desde el teclado.
+                letter @ ('a'..='z' | 'A'..='Z') => Some(Message::CharacterPressed(letter)),
+                '=' => Some(Message::CharacterPressed('=')),
+                _ => None,
+            },
+            keyboard::Key::Named(keyboard::key::Named::Enter) => Some(Message::EqualsPressed),
+            keyboard::Key::Named(keyboard::key::Named::Backspace) => Some(Message::Backspace),
+            keyboard::Key::Named(keyboard::key::Named::Escape) => Some(Message::ClearPressed),
+            keyboard::Key::Named(keyboard::key::Named::Delete) => Some(Message::ClearEntry),
+            _ => None,
+        })
+    }
 }
 
 // # 7. Lexer (Analizador Léxico)
@@ -247,6 +438,7 @@ impl Application for Calculator {
 #[derive(Debug, PartialEq, Clone)]
 enum Token {
     Number(f64),
+    Ident(String),
     Plus,
     Minus,
     Multiply,
@@ -254,6 +446,9 @@ enum Token {
     LParen,
     RParen,
     UnaryMinus,
+    Bang,
+    Assign,
+    Caret,
 }
 
 fn lexer(input: &str) -> Result<Vec<Token>, String> {
@@ -276,6 +471,23 @@ fn lexer(input: &str) -> Result<Vec<Token>, String> {
                 tokens.push(Token::Number(num));
                 last_token_was_operator = false;
             }
+            'a'..='z' | 'A'..='Z' => {
+                // Acumulamos una racha de caracteres alfabéticos: nombres de función
+                // (`sin`, `cos`, `sqrt`, ...) o nombres de variable (`x`, `M`, `Ans`).
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphabetic() {
+                        ident.push(chars.next().unwrap());
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+                last_token_was_operator = false;
+            }
+            '!' => { tokens.push(Token::Bang); chars.next(); last_token_was_operator = false; },
+            '=' => { tokens.push(Token::Assign); chars.next(); last_token_was_operator = true; },
+            '^' => { tokens.push(Token::Caret); chars.next(); last_token_was_operator = true; },
             '+' => { tokens.push(Token::Plus); chars.next(); last_token_was_operator = true; },
             '-' => {
                 // Aquí diferenciamos entre el signo de resta y el de un número negativo.
@@ -315,6 +527,16 @@ enum Expr {
         left: Box<Expr>,
         right: Box<Expr>,
     },
+    FunctionCall {
+        name: String,
+        arg: Box<Expr>,
+    },
+    Factorial(Box<Expr>),
+    Variable(String),
+    Assign {
+        name: String,
+        value: Box<Expr>,
+    },
 }
 
 struct Parser {
@@ -355,7 +577,21 @@ impl Parser {
 
     // `parse` es el punto de entrada del `parser`.
     fn parse(&mut self) -> Result<Expr, String> {
-        let expr = self.parse_expression()?;
+        // Una asignación tiene la forma `Ident = <expr>`; se reconoce antes de caer
+        // en la gramática normal de expresiones para no confundir `x` con una llamada
+        // a función ni con una variable de solo lectura.
+        if let (Some(Token::Ident(name)), Some(Token::Assign)) = (self.tokens.first(), self.tokens.get(1)) {
+            let name = name.clone();
+            self.position = 2;
+            let value = self.parse_bp(0)?;
+            return if self.position < self.tokens.len() {
+                Err(format!("Tokens inesperados al final de la expresión: {:?}", &self.tokens[self.position..]))
+            } else {
+                Ok(Expr::Assign { name, value: Box::new(value) })
+            };
+        }
+
+        let expr = self.parse_bp(0)?;
         if self.position < self.tokens.len() {
             Err(format!("Tokens inesperados al final de la expresión: {:?}", &self.tokens[self.position..]))
         } else {
@@ -363,47 +599,85 @@ impl Parser {
         }
     }
 
-    // Las siguientes funciones implementan la precedencia de operadores (PEMDAS/BODMAS).
-    fn parse_expression(&mut self) -> Result<Expr, String> {
-        let mut expr = self.parse_term()?;
-        while let Some(token) = self.peek() {
-            match token {
-                Token::Plus | Token::Minus => {
-                    let op = self.next().unwrap();
-                    let right = self.parse_term()?;
-                    expr = Expr::BinaryOp { op, left: Box::new(expr), right: Box::new(right) };
-                }
-                _ => break,
-            }
+    // Potencia de ligadura de cada operador infijo, como `(izquierda, derecha)`.
+    // A mayor número, mayor precedencia. Que la potencia derecha de `^` (5) sea
+    // menor que su potencia izquierda (6) es lo que la hace asociativa por la
+    // derecha: un `^` encadenado a la derecha se admite (potencia izquierda del
+    // siguiente `^`, 6, sigue siendo >= 5), pero uno encadenado a la izquierda no.
+    fn binding_power(token: &Token) -> Option<(u8, u8)> {
+        match token {
+            Token::Plus | Token::Minus => Some((1, 2)),
+            Token::Multiply | Token::Divide => Some((3, 4)),
+            Token::Caret => Some((6, 5)),
+            _ => None,
         }
-        Ok(expr)
     }
 
-    fn parse_term(&mut self) -> Result<Expr, String> {
-        let mut expr = self.parse_factor()?;
+    // Potencia de ligadura del `!` postfijo y del `-` prefijo: ambas por encima
+    // de `^` para que se apliquen antes, pero el unario se parsea con la suya
+    // propia para que `-2 ^ 2` siga agrupando como `(-2) ^ 2`, igual que antes.
+    const POSTFIX_BP: u8 = 8;
+    const PREFIX_BP: u8 = 9;
+
+    // El corazón del parser de precedencia: parsea un prefijo (`nud`) y luego,
+    // mientras el siguiente token sea un operador (postfijo o infijo) cuya
+    // potencia de ligadura izquierda sea al menos `min_bp`, lo consume y pliega
+    // el resultado. Añadir un operador nuevo es, en general, una entrada más en
+    // `binding_power` en vez de un nuevo método de parseo.
+    fn parse_bp(&mut self, min_bp: u8) -> Result<Expr, String> {
+        let mut lhs = self.parse_nud()?;
+
         while let Some(token) = self.peek() {
-            match token {
-                Token::Multiply | Token::Divide => {
-                    let op = self.next().unwrap();
-                    let right = self.parse_factor()?;
-                    expr = Expr::BinaryOp { op, left: Box::new(expr), right: Box::new(right) };
+            let token = token.clone();
+
+            if token == Token::Bang {
+                if Self::POSTFIX_BP < min_bp {
+                    break;
                 }
-                _ => break,
+                self.next();
+                lhs = Expr::Factorial(Box::new(lhs));
+                continue;
+            }
+
+            let (l_bp, r_bp) = match Self::binding_power(&token) {
+                Some(bp) => bp,
+                None => break,
+            };
+            if l_bp < min_bp {
+                break;
             }
+            self.next();
+            let rhs = self.parse_bp(r_bp)?;
+            lhs = Expr::BinaryOp { op: token, left: Box::new(lhs), right: Box::new(rhs) };
         }
-        Ok(expr)
+
+        Ok(lhs)
     }
 
-    fn parse_factor(&mut self) -> Result<Expr, String> {
+    // `parse_nud` ("null denotation") parsea lo que puede empezar una expresión:
+    // un número, una variable o llamada a función, un paréntesis, o un `-` unario.
+    fn parse_nud(&mut self) -> Result<Expr, String> {
         let token = self.next().ok_or("Se esperaba un número o paréntesis, pero se encontró el final de la entrada")?;
         match token {
             Token::Number(value) => Ok(Expr::Literal(value)),
+            Token::Ident(name) => {
+                // Un identificador seguido de `(` es una llamada a función, ej. `sin(0)`;
+                // de lo contrario es una variable que se busca en el entorno.
+                if let Some(Token::LParen) = self.peek() {
+                    self.next();
+                    let arg = self.parse_bp(0)?;
+                    self.expect(Token::RParen)?;
+                    Ok(Expr::FunctionCall { name, arg: Box::new(arg) })
+                } else {
+                    Ok(Expr::Variable(name))
+                }
+            }
             Token::UnaryMinus => {
-                let expr = self.parse_factor()?;
+                let expr = self.parse_bp(Self::PREFIX_BP)?;
                 Ok(Expr::UnaryOp { op: Token::UnaryMinus, expr: Box::new(expr) })
             }
             Token::LParen => {
-                let expr = self.parse_expression()?;
+                let expr = self.parse_bp(0)?;
                 self.expect(Token::RParen)?;
                 Ok(expr)
             }
@@ -416,19 +690,19 @@ impl Parser {
 //
 // Esta función recorre el AST de forma recursiva y calcula el resultado final de la
 // expresión.
-fn evaluate_ast(expr: &Expr) -> Result<f64, String> {
+fn evaluate_ast(expr: &Expr, env: &mut HashMap<String, f64>) -> Result<f64, String> {
     match expr {
         Expr::Literal(value) => Ok(*value),
         Expr::UnaryOp { op, expr } => {
-            let val = evaluate_ast(expr)?;
+            let val = evaluate_ast(expr, env)?;
             match op {
                 Token::UnaryMinus => Ok(-val),
                 _ => Err(format!("Operador unario inesperado en AST: {:?}", op)),
             }
         }
         Expr::BinaryOp { op, left, right } => {
-            let left_val = evaluate_ast(left)?;
-            let right_val = evaluate_ast(right)?;
+            let left_val = evaluate_ast(left, env)?;
+            let right_val = evaluate_ast(right, env)?;
             match op {
                 Token::Plus => Ok(left_val + right_val),
                 Token::Minus => Ok(left_val - right_val),
@@ -440,9 +714,126 @@ fn evaluate_ast(expr: &Expr) -> Result<f64, String> {
                         Ok(left_val / right_val)
                     }
                 },
+                Token::Caret => Ok(left_val.powf(right_val)),
                 _ => Err(format!("Operador binario inesperado en AST: {:?}", op)),
             }
         }
+        Expr::FunctionCall { name, arg } => {
+            let val = evaluate_ast(arg, env)?;
+            match name.as_str() {
+                "sin" => Ok(val.sin()),
+                "cos" => Ok(val.cos()),
+                "tan" => Ok(val.tan()),
+                "sqrt" => Ok(val.sqrt()),
+                "ln" => Ok(val.ln()),
+                _ => Err(format!("Función desconocida: {}", name)),
+            }
+        }
+        Expr::Factorial(expr) => {
+            let val = evaluate_ast(expr, env)?;
+            if val < 0.0 || val.fract() != 0.0 {
+                return Err(format!("El factorial solo está definido para enteros no negativos, no para {}", val));
+            }
+            // Por encima de 170! el resultado ya desborda a infinito en `f64`, así
+            // que rechazamos antes de intentar el bucle en vez de quedarnos
+            // calculando millones de iteraciones para un número que no cabe.
+            if val > 170.0 {
+                return Err(format!("El factorial de {} es demasiado grande para calcularlo", val));
+            }
+            let n = val as u64;
+            Ok((1..=n).fold(1.0, |acc, i| acc * i as f64))
+        }
+        Expr::Variable(name) => {
+            env.get(name).copied().ok_or_else(|| format!("Variable desconocida: {}", name))
+        }
+        Expr::Assign { name, value } => {
+            let val = evaluate_ast(value, env)?;
+            env.insert(name.clone(), val);
+            Ok(val)
+        }
+    }
+}
+
+// # 9.1. Backend alternativo: máquina de pila (bytecode)
+//
+// Además de recorrer el AST directamente (`evaluate_ast`), lo compilamos a una
+// lista plana de instrucciones para una máquina de pila. Es un segundo backend
+// con fines didácticos y para, más adelante, poder cachear y reevaluar una
+// expresión sin volver a recorrer el árbol.
+//
+// Solo cubre el subconjunto aritmético puro del AST (literales, operadores
+// binarios `+ - * /` y el menos unario); las funciones, el factorial, la
+// potencia, las variables y las asignaciones no tienen representación en
+// bytecode y `compile` devuelve un error descriptivo si los encuentra.
+#[derive(Debug, PartialEq, Clone)]
+enum Instr {
+    Push(f64),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+}
+
+fn compile(expr: &Expr, out: &mut Vec<Instr>) -> Result<(), String> {
+    match expr {
+        Expr::Literal(value) => {
+            out.push(Instr::Push(*value));
+            Ok(())
+        }
+        Expr::UnaryOp { op: Token::UnaryMinus, expr } => {
+            compile(expr, out)?;
+            out.push(Instr::Neg);
+            Ok(())
+        }
+        Expr::BinaryOp { op, left, right } => {
+            compile(left, out)?;
+            compile(right, out)?;
+            match op {
+                Token::Plus => out.push(Instr::Add),
+                Token::Minus => out.push(Instr::Sub),
+                Token::Multiply => out.push(Instr::Mul),
+                Token::Divide => out.push(Instr::Div),
+                _ => return Err(format!("La VM no soporta el operador {:?}", op)),
+            }
+            Ok(())
+        }
+        _ => Err("La VM solo soporta literales y operadores aritméticos básicos".to_string()),
+    }
+}
+
+fn run_vm(code: &[Instr]) -> Result<f64, String> {
+    let mut stack: Vec<f64> = Vec::new();
+    for instr in code {
+        match instr {
+            Instr::Push(value) => stack.push(*value),
+            Instr::Neg => {
+                let val = stack.pop().ok_or("Pila vacía al ejecutar Neg")?;
+                stack.push(-val);
+            }
+            Instr::Add | Instr::Sub | Instr::Mul | Instr::Div => {
+                let right = stack.pop().ok_or("Pila vacía: falta el operando derecho")?;
+                let left = stack.pop().ok_or("Pila vacía: falta el operando izquierdo")?;
+                let result = match instr {
+                    Instr::Add => left + right,
+                    Instr::Sub => left - right,
+                    Instr::Mul => left * right,
+                    Instr::Div => {
+                        if right == 0.0 {
+                            return Err("División por cero no permitida".to_string());
+                        }
+                        left / right
+                    }
+                    _ => unreachable!(),
+                };
+                stack.push(result);
+            }
+        }
+    }
+    if stack.len() == 1 {
+        Ok(stack[0])
+    } else {
+        Err(format!("Pila mal formada al finalizar la ejecución: {:?}", stack))
     }
 }
 